@@ -25,60 +25,235 @@ fn is_copy(t: &BaseTyp) -> bool {
     }
 }
 
+fn is_integer(t: &BaseTyp) -> bool {
+    match t {
+        BaseTyp::UInt128
+        | BaseTyp::Int128
+        | BaseTyp::UInt64
+        | BaseTyp::Int64
+        | BaseTyp::UInt32
+        | BaseTyp::Int32
+        | BaseTyp::UInt16
+        | BaseTyp::Int16
+        | BaseTyp::UInt8
+        | BaseTyp::Int8
+        | BaseTyp::Usize
+        | BaseTyp::Isize => true,
+        _ => false,
+    }
+}
+
+/// The `BaseTyp` a literal is given when nothing around it demands a more
+/// specific one, mirroring how an unsuffixed Rust integer literal defaults
+/// to `i32`.
+fn literal_default_typ(lit: &Literal) -> BaseTyp {
+    match lit {
+        Literal::Bool(_) => BaseTyp::Bool,
+        Literal::Int128(_) => BaseTyp::Int128,
+        Literal::UInt128(_) => BaseTyp::UInt128,
+        Literal::Int64(_) => BaseTyp::Int64,
+        Literal::UInt64(_) => BaseTyp::UInt64,
+        Literal::Int32(_) => BaseTyp::Int32,
+        Literal::UInt32(_) => BaseTyp::UInt32,
+        Literal::Int16(_) => BaseTyp::Int16,
+        Literal::UInt16(_) => BaseTyp::UInt16,
+        Literal::Int8(_) => BaseTyp::Int8,
+        Literal::UInt8(_) => BaseTyp::UInt8,
+        Literal::Usize(_) => BaseTyp::Usize,
+        Literal::Isize(_) => BaseTyp::Isize,
+    }
+}
+
+/// The numeric value carried by an integer literal, used to range-check it
+/// against an expected type it gets coerced into. `UInt128` is skipped like
+/// `Bool`, rather than cast through `i128` like the other arms: a literal
+/// with the high bit set (>= 2^127) would wrap to a negative `i128` and
+/// then wrongly fail `fits_in_base_typ`'s `value >= 0` check for
+/// `UInt128`. That range check would be redundant anyway — a `UInt128`
+/// literal's default type is already `UInt128` (see `literal_default_typ`),
+/// so the only expected type it can reach this far with is `UInt128`
+/// itself, which it always fits by construction.
+fn literal_value(lit: &Literal) -> Option<i128> {
+    match lit {
+        Literal::Bool(_) => None,
+        Literal::UInt128(_) => None,
+        Literal::Int128(x) => Some(*x),
+        Literal::Int64(x) => Some(*x as i128),
+        Literal::UInt64(x) => Some(*x as i128),
+        Literal::Int32(x) => Some(*x as i128),
+        Literal::UInt32(x) => Some(*x as i128),
+        Literal::Int16(x) => Some(*x as i128),
+        Literal::UInt16(x) => Some(*x as i128),
+        Literal::Int8(x) => Some(*x as i128),
+        Literal::UInt8(x) => Some(*x as i128),
+        Literal::Usize(x) => Some(*x as i128),
+        Literal::Isize(x) => Some(*x as i128),
+    }
+}
+
+fn fits_in_base_typ(value: i128, t: &BaseTyp) -> bool {
+    match t {
+        BaseTyp::UInt128 => value >= 0,
+        BaseTyp::Int128 => true,
+        BaseTyp::UInt64 => value >= 0 && value <= u64::MAX as i128,
+        BaseTyp::Int64 => value >= i64::MIN as i128 && value <= i64::MAX as i128,
+        BaseTyp::UInt32 => value >= 0 && value <= u32::MAX as i128,
+        BaseTyp::Int32 => value >= i32::MIN as i128 && value <= i32::MAX as i128,
+        BaseTyp::UInt16 => value >= 0 && value <= u16::MAX as i128,
+        BaseTyp::Int16 => value >= i16::MIN as i128 && value <= i16::MAX as i128,
+        BaseTyp::UInt8 => value >= 0 && value <= u8::MAX as i128,
+        BaseTyp::Int8 => value >= i8::MIN as i128 && value <= i8::MAX as i128,
+        BaseTyp::Usize => value >= 0 && value <= usize::MAX as i128,
+        BaseTyp::Isize => value >= isize::MIN as i128 && value <= isize::MAX as i128,
+        _ => false,
+    }
+}
+
+/// A bare, unsuffixed integer literal defaults to `Int32` (see
+/// `literal_default_typ`, mirroring Rust's own default), so only a literal
+/// whose default type is `Int32` is still unresolved and free to pick up a
+/// different concrete integer type from context. A literal that defaults
+/// to anything else (`5u8`, `5i64`, ...) was explicitly suffixed in the
+/// source and is pinned to that type from then on, just like a named
+/// variable would be.
+fn is_unresolved_lit(e: &Expression) -> bool {
+    match e {
+        Expression::Lit(lit) => equal_base_typs(&literal_default_typ(lit), &BaseTyp::Int32),
+        _ => false,
+    }
+}
+
+fn equal_base_typs(t1: &BaseTyp, t2: &BaseTyp) -> bool {
+    match (t1, t2) {
+        (BaseTyp::Unit, BaseTyp::Unit) => true,
+        (BaseTyp::Bool, BaseTyp::Bool) => true,
+        (BaseTyp::UInt128, BaseTyp::UInt128) => true,
+        (BaseTyp::Int128, BaseTyp::Int128) => true,
+        (BaseTyp::UInt64, BaseTyp::UInt64) => true,
+        (BaseTyp::Int64, BaseTyp::Int64) => true,
+        (BaseTyp::UInt32, BaseTyp::UInt32) => true,
+        (BaseTyp::Int32, BaseTyp::Int32) => true,
+        (BaseTyp::UInt16, BaseTyp::UInt16) => true,
+        (BaseTyp::Int16, BaseTyp::Int16) => true,
+        (BaseTyp::UInt8, BaseTyp::UInt8) => true,
+        (BaseTyp::Int8, BaseTyp::Int8) => true,
+        (BaseTyp::Usize, BaseTyp::Usize) => true,
+        (BaseTyp::Isize, BaseTyp::Isize) => true,
+        (BaseTyp::Seq(tc1), BaseTyp::Seq(tc2)) => equal_base_typs(&tc1.0, &tc2.0),
+        (BaseTyp::Named(p1), BaseTyp::Named(p2)) => {
+            p1.location.len() == p2.location.len()
+                && (p1
+                    .location
+                    .iter()
+                    .zip(p2.location.iter())
+                    .all(|(i1, i2)| i1 == i2))
+                && match (&p1.arg, &p2.arg) {
+                    (None, None) => true,
+                    (Some(tc1), Some(tc2)) => equal_base_typs(tc1, tc2),
+                    _ => false,
+                }
+        }
+        (BaseTyp::Tuple(ts1), BaseTyp::Tuple(ts2)) => {
+            ts1.len() == ts2.len()
+                && ts1
+                    .iter()
+                    .zip(ts2.iter())
+                    .all(|((tc1, _), (tc2, _))| equal_base_typs(tc1, tc2))
+        }
+        _ => false,
+    }
+}
+
+/// Extracts the element type of an array-like `BaseTyp`: either a `Seq`,
+/// whose cell type carries its own span, or a fixed-length named array
+/// declared over an element type, which has none, so `fallback_span` (the
+/// span of the array expression itself) is reused instead. Mirrors the
+/// named-array/`Seq` interchange `coerce` already allows at call
+/// boundaries, so indexing and assigning into a named array works the
+/// same way passing one to a `Seq`-typed parameter does.
+fn array_cell_typ(t: &BaseTyp, fallback_span: Span) -> Option<(BaseTyp, Span)> {
+    match t {
+        BaseTyp::Seq(cell_t) => Some((**cell_t).clone()),
+        BaseTyp::Named(p) => p.arg.as_ref().map(|arg| ((**arg).clone(), fallback_span)),
+        _ => None,
+    }
+}
+
 fn equal_types(t1: &Typ, t2: &Typ) -> bool {
     match (&(t1.0).0, &(t2.0).0) {
         (Borrowing::Consumed, Borrowing::Consumed) | (Borrowing::Borrowed, Borrowing::Borrowed) => {
-            match (&(t1.1).0, &(t2.1).0) {
-                (BaseTyp::Unit, BaseTyp::Unit) => true,
-                (BaseTyp::Bool, BaseTyp::Bool) => true,
-                (BaseTyp::UInt128, BaseTyp::UInt128) => true,
-                (BaseTyp::Int128, BaseTyp::Int128) => true,
-                (BaseTyp::UInt64, BaseTyp::UInt64) => true,
-                (BaseTyp::Int64, BaseTyp::Int64) => true,
-                (BaseTyp::UInt32, BaseTyp::UInt32) => true,
-                (BaseTyp::Int32, BaseTyp::Int32) => true,
-                (BaseTyp::UInt16, BaseTyp::UInt16) => true,
-                (BaseTyp::Int16, BaseTyp::Int16) => true,
-                (BaseTyp::UInt8, BaseTyp::UInt8) => true,
-                (BaseTyp::Int8, BaseTyp::Int8) => true,
-                (BaseTyp::Usize, BaseTyp::Usize) => true,
-                (BaseTyp::Isize, BaseTyp::Isize) => true,
-                (BaseTyp::Seq(tc1), BaseTyp::Seq(tc2)) => equal_types(
-                    &(((Borrowing::Consumed, (t1.1).1)), *tc1.clone()),
-                    &(((Borrowing::Consumed, (t2.1).1)), *tc2.clone()),
-                ),
-                (BaseTyp::Named(p1), BaseTyp::Named(p2)) => {
-                    p1.location.len() == p2.location.len()
-                        && (p1
-                            .location
-                            .iter()
-                            .zip(p2.location.iter())
-                            .all(|(i1, i2)| i1 == i2))
-                        && match (&p1.arg, &p2.arg) {
-                            (None, None) => true,
-                            (Some(tc1), Some(tc2)) => equal_types(
-                                &(((Borrowing::Consumed, (t1.1).1)), (*tc1.clone(), (t1.1).1)),
-                                &(((Borrowing::Consumed, (t2.1).1)), (*tc2.clone(), (t2.1).1)),
-                            ),
-                            _ => false,
-                        }
-                }
-                (BaseTyp::Tuple(ts1), BaseTyp::Tuple(ts2)) => {
-                    ts1.len() == ts2.len()
-                        && ts1.iter().zip(ts2.iter()).all(|(tc1, tc2)| {
-                            equal_types(
-                                &(((Borrowing::Consumed, (t1.1).1)), tc1.clone()),
-                                &(((Borrowing::Consumed, (t2.1).1)), tc2.clone()),
-                            )
-                        })
-                }
-                _ => false,
-            }
+            equal_base_typs(&(t1.1).0, &(t2.1).0)
         }
         _ => false,
     }
 }
 
+/// A strictly wider relation than [`equal_types`], used at the `FuncCall`,
+/// `MethodCall`, and `LetBinding` boundaries rather than for binary-operator
+/// operands, which must stay exactly matched. Besides exact equality, two
+/// controlled widenings are accepted: passing a cheap `Copy` scalar by value
+/// where a borrow of it is expected (an implicit auto-ref), and
+/// interchanging a fixed-length named array with the `Seq` it is
+/// element-compatible with (the backend erases named arrays to sequences
+/// anyway).
+fn coerce(from: &Typ, to: &Typ) -> TypecheckingResult<()> {
+    if equal_types(from, to) {
+        return Ok(());
+    }
+    if let (Borrowing::Consumed, Borrowing::Borrowed) = (&(from.0).0, &(to.0).0) {
+        if is_copy(&(from.1).0) && equal_base_typs(&(from.1).0, &(to.1).0) {
+            return Ok(());
+        }
+    }
+    let same_borrowing = matches!(
+        (&(from.0).0, &(to.0).0),
+        (Borrowing::Consumed, Borrowing::Consumed) | (Borrowing::Borrowed, Borrowing::Borrowed)
+    );
+    if same_borrowing {
+        let named_array_seq_compatible = match (&(from.1).0, &(to.1).0) {
+            (BaseTyp::Named(p), BaseTyp::Seq(cell)) | (BaseTyp::Seq(cell), BaseTyp::Named(p)) => p
+                .arg
+                .as_ref()
+                .map_or(false, |arg| equal_base_typs(arg, &cell.0)),
+            _ => false,
+        };
+        if named_array_seq_compatible {
+            return Ok(());
+        }
+    }
+    Err(())
+}
+
+/// Reports a `coerce` failure at a `FuncCall`/`MethodCall` argument. Any
+/// difference in borrowing direction gets a dedicated "expected a borrow" or
+/// "superflous borrow" diagnostic, whatever the base types are; only a
+/// mismatch between two equally-borrowed types reaches the generic
+/// "expected type ..., got ..." message, which prints the borrowing tag and
+/// base type of both sides.
+fn report_coerce_failure(sess: &Session, arg_span: Span, arg_t: &Typ, sig_t: &Typ) {
+    match ((arg_t.0).0, (sig_t.0).0) {
+        (Borrowing::Consumed, Borrowing::Borrowed) => {
+            sess.span_err(arg_span, "expected a borrow here but didn't find one");
+        }
+        (Borrowing::Borrowed, Borrowing::Consumed) => {
+            sess.span_err(arg_span, "superflous borrow here, argument is consumed");
+        }
+        _ => {
+            sess.span_err(
+                arg_span,
+                format!(
+                    "expected type {}{}, got {}{}",
+                    (sig_t.0).0,
+                    (sig_t.1).0,
+                    (arg_t.0).0,
+                    (arg_t.1).0
+                )
+                .as_str(),
+            );
+        }
+    }
+}
+
 #[derive(Clone, Hash, PartialEq, Eq)]
 enum FnKey {
     Static(Ident),
@@ -91,6 +266,12 @@ type VarContext = HashMap<Ident, Typ>;
 
 type VarSet = HashSet<Ident>;
 
+/// Records, for each variable that has been moved out of, the span of the
+/// expression that consumed it. Consulted whenever a later use of that
+/// identifier would otherwise be reported as "unknown", so that the real
+/// diagnostic ("use of moved value") can point back at the move.
+type MovedContext = HashMap<Ident, Span>;
+
 pub type TypecheckingResult<T> = Result<T, ()>;
 
 fn check_vec<T>(v: Vec<TypecheckingResult<T>>) -> TypecheckingResult<Vec<T>> {
@@ -101,22 +282,61 @@ fn check_vec<T>(v: Vec<TypecheckingResult<T>>) -> TypecheckingResult<Vec<T>> {
     }
 }
 
+/// Restricts `ctx` to the keys also present in `scope`, keeping `ctx`'s
+/// values. Used when a nested block's resulting `VarContext` is handed back
+/// to an enclosing scope: any extra bindings the block introduced (its own
+/// `let`s) go out of scope there, while the consumption of outer variables
+/// must still be visible to the caller.
+fn restrict<V: Clone>(ctx: &HashMap<Ident, V>, scope: &VarContext) -> HashMap<Ident, V> {
+    ctx.clone()
+        .into_iter()
+        .filter(|(id, _)| scope.contains_key(id))
+        .collect()
+}
+
+/// Merges the outgoing `VarContext`s of the two branches of a conditional
+/// into the context visible after it. A binding survives only if it is
+/// still live, with the same type, on both paths: a non-`Copy` value
+/// consumed on either branch is dropped from the merge, so that using it
+/// after the conditional is uniformly rejected regardless of which branch
+/// actually ran.
+fn merge_var_context(ctx1: &VarContext, ctx2: &VarContext) -> VarContext {
+    ctx1.clone()
+        .into_iter()
+        .filter_map(|(id, t1)| match ctx2.get(&id) {
+            Some(t2) if equal_types(&t1, t2) => Some((id, t1)),
+            _ => None,
+        })
+        .collect()
+}
+
 fn typecheck_expression(
     sess: &Session,
     (e, span): &Spanned<Expression>,
 
     fn_context: &FnContext,
     var_context: &VarContext,
-) -> TypecheckingResult<(Typ, VarContext)> {
+    moved_context: &MovedContext,
+    expected: Option<&BaseTyp>,
+) -> TypecheckingResult<(Typ, VarContext, MovedContext)> {
     match e {
         Expression::Tuple(args) => {
             let mut var_context = var_context.clone();
+            let mut moved_context = moved_context.clone();
             let typ_args = args
                 .iter()
                 .map(|arg| {
-                    let (((arg_typ_borrowing, _), arg_typ), new_var_context) =
-                        typecheck_expression(sess, arg, fn_context, &var_context)?;
+                    let (((arg_typ_borrowing, _), arg_typ), new_var_context, new_moved_context) =
+                        typecheck_expression(
+                            sess,
+                            arg,
+                            fn_context,
+                            &var_context,
+                            &moved_context,
+                            None,
+                        )?;
                     var_context = new_var_context;
+                    moved_context = new_moved_context;
                     match arg_typ_borrowing {
                         Borrowing::Borrowed => {
                             sess.span_err(
@@ -136,27 +356,43 @@ fn typecheck_expression(
                     (BaseTyp::Tuple(typ_args), span.clone()),
                 ),
                 var_context,
+                moved_context,
             ))
         }
         Expression::Named(path) => match (path.arg.as_ref(), path.location.len()) {
             (None, 1) => {
                 let (id, _) = &path.location[0];
                 match var_context.get(id) {
-                    None => {
-                        sess.span_err(*span, format!("the variable {} is unknown", id).as_str());
-                        Err(())
-                    }
+                    None => match moved_context.get(id) {
+                        Some(moved_span) => {
+                            sess.struct_span_err(
+                                *span,
+                                format!("use of moved value `{}`", id).as_str(),
+                            )
+                            .span_note(*moved_span, format!("value `{}` was moved here", id).as_str())
+                            .emit();
+                            Err(())
+                        }
+                        None => {
+                            sess.span_err(
+                                *span,
+                                format!("the variable {} is unknown", id).as_str(),
+                            );
+                            Err(())
+                        }
+                    },
                     Some(t) => {
                         // This is where linearity kicks in
                         if let Borrowing::Consumed = (t.0).0 {
                             if is_copy(&(t.1).0) {
-                                Ok((t.clone(), var_context.clone()))
+                                Ok((t.clone(), var_context.clone(), moved_context.clone()))
                             } else {
                                 let new_var_context = var_context.without(&id);
-                                Ok((t.clone(), new_var_context))
+                                let new_moved_context = moved_context.update(id.clone(), *span);
+                                Ok((t.clone(), new_var_context, new_moved_context))
                             }
                         } else {
-                            Ok((t.clone(), var_context.clone()))
+                            Ok((t.clone(), var_context.clone(), moved_context.clone()))
                         }
                     }
                 }
@@ -167,8 +403,47 @@ fn typecheck_expression(
             }
         },
         Expression::Binary(_, e1, e2) => {
-            let (t1, var_context) = typecheck_expression(sess, e1, fn_context, var_context)?;
-            let (t2, var_context) = typecheck_expression(sess, e2, fn_context, &var_context)?;
+            let (t1, var_context, moved_context) =
+                typecheck_expression(sess, e1, fn_context, var_context, moved_context, expected)?;
+            let (t2, var_context, moved_context) = typecheck_expression(
+                sess,
+                e2,
+                fn_context,
+                &var_context,
+                &moved_context,
+                Some(&(t1.1).0),
+            )?;
+            // Neither operand constrained the other yet if both were typed
+            // independently of each other. When exactly one side is a bare,
+            // unresolved integer literal and the other has a concrete,
+            // different integer type, re-typecheck the literal demanding
+            // that type instead of its default, so `x + 1` type-checks
+            // against `x: UInt32` regardless of which side the literal is
+            // on. An explicitly suffixed literal (`1u8`) is not unresolved
+            // and is left to mismatch below like any other fixed type.
+            let (t1, t2, var_context, moved_context) =
+                match (
+                    is_unresolved_lit(&e1.0),
+                    is_unresolved_lit(&e2.0),
+                    (t1.1).0 != (t2.1).0,
+                ) {
+                    (true, false, true) if is_integer(&(t1.1).0) && is_integer(&(t2.1).0) => {
+                        let (t1, var_context, moved_context) = typecheck_expression(
+                            sess,
+                            e1,
+                            fn_context,
+                            &var_context,
+                            &moved_context,
+                            Some(&(t2.1).0),
+                        )?;
+                        (t1, t2, var_context, moved_context)
+                    }
+                    // If `e2` is the bare literal, it was already checked
+                    // above with `e1`'s type as its expected type, so it
+                    // only remains mismatched here when `e1` itself could
+                    // not be resolved to an integer type.
+                    _ => (t1, t2, var_context, moved_context),
+                };
             if !equal_types(&t1, &t2) {
                 sess.span_err(
                     *span,
@@ -180,110 +455,52 @@ fn typecheck_expression(
                 );
                 Err(())
             } else {
-                Ok((t1, var_context))
+                Ok((t1, var_context, moved_context))
             }
         }
-        Expression::Unary(_, e1) => typecheck_expression(sess, e1, fn_context, var_context),
-        Expression::Lit(lit) => match lit {
-            Literal::Bool(_) => Ok((
-                (
-                    (Borrowing::Consumed, span.clone()),
-                    (BaseTyp::Bool, span.clone()),
-                ),
-                var_context.clone(),
-            )),
-            Literal::Int128(_) => Ok((
-                (
-                    (Borrowing::Consumed, span.clone()),
-                    (BaseTyp::Int128, span.clone()),
-                ),
-                var_context.clone(),
-            )),
-            Literal::UInt128(_) => Ok((
-                (
-                    (Borrowing::Consumed, span.clone()),
-                    (BaseTyp::UInt128, span.clone()),
-                ),
-                var_context.clone(),
-            )),
-            Literal::Int64(_) => Ok((
-                (
-                    (Borrowing::Consumed, span.clone()),
-                    (BaseTyp::Int64, span.clone()),
-                ),
-                var_context.clone(),
-            )),
-            Literal::UInt64(_) => Ok((
-                (
-                    (Borrowing::Consumed, span.clone()),
-                    (BaseTyp::UInt64, span.clone()),
-                ),
-                var_context.clone(),
-            )),
-            Literal::Int32(_) => Ok((
-                (
-                    (Borrowing::Consumed, span.clone()),
-                    (BaseTyp::Int32, span.clone()),
-                ),
-                var_context.clone(),
-            )),
-            Literal::UInt32(_) => Ok((
-                (
-                    (Borrowing::Consumed, span.clone()),
-                    (BaseTyp::UInt32, span.clone()),
-                ),
-                var_context.clone(),
-            )),
-            Literal::Int16(_) => Ok((
-                (
-                    (Borrowing::Consumed, span.clone()),
-                    (BaseTyp::Int16, span.clone()),
-                ),
-                var_context.clone(),
-            )),
-            Literal::UInt16(_) => Ok((
-                (
-                    (Borrowing::Consumed, span.clone()),
-                    (BaseTyp::UInt16, span.clone()),
-                ),
-                var_context.clone(),
-            )),
-            Literal::Int8(_) => Ok((
-                (
-                    (Borrowing::Consumed, span.clone()),
-                    (BaseTyp::Int8, span.clone()),
-                ),
-                var_context.clone(),
-            )),
-            Literal::UInt8(_) => Ok((
-                (
-                    (Borrowing::Consumed, span.clone()),
-                    (BaseTyp::UInt8, span.clone()),
-                ),
-                var_context.clone(),
-            )),
-            Literal::Usize(_) => Ok((
-                (
-                    (Borrowing::Consumed, span.clone()),
-                    (BaseTyp::Usize, span.clone()),
-                ),
-                var_context.clone(),
-            )),
-            Literal::Isize(_) => Ok((
+        Expression::Unary(_, e1) => {
+            typecheck_expression(sess, e1, fn_context, var_context, moved_context, expected)
+        }
+        Expression::Lit(lit) => {
+            let default_typ = literal_default_typ(lit);
+            let unresolved = equal_base_typs(&default_typ, &BaseTyp::Int32);
+            let resolved_typ = match expected {
+                Some(exp_t)
+                    if is_integer(&default_typ)
+                        && is_integer(exp_t)
+                        && (unresolved || equal_base_typs(&default_typ, exp_t)) =>
+                {
+                    match literal_value(lit) {
+                        Some(value) if fits_in_base_typ(value, exp_t) => exp_t.clone(),
+                        Some(_) => {
+                            sess.span_err(
+                                *span,
+                                format!("integer literal does not fit in type {}", exp_t).as_str(),
+                            );
+                            return Err(());
+                        }
+                        None => default_typ,
+                    }
+                }
+                _ => default_typ,
+            };
+            Ok((
                 (
                     (Borrowing::Consumed, span.clone()),
-                    (BaseTyp::Isize, span.clone()),
+                    (resolved_typ, span.clone()),
                 ),
                 var_context.clone(),
-            )),
-        },
+                moved_context.clone(),
+            ))
+        }
         Expression::ArrayIndex(e1, e2) => {
-            let (t1, var_context) = typecheck_expression(sess, e1, fn_context, var_context)?;
-            let (t2, var_context) = typecheck_expression(sess, e2, fn_context, &var_context)?;
+            let (t1, var_context, moved_context) =
+                typecheck_expression(sess, e1, fn_context, var_context, moved_context, None)?;
+            let (t2, var_context, moved_context) =
+                typecheck_expression(sess, e2, fn_context, &var_context, &moved_context, None)?;
             // We ignore t1.0 because we can read from both consumed and borrowed array types
-            match (t1.1).0 {
-                BaseTyp::Seq(seq_t) => {
-                    let (cell_t, cell_t_span) = *seq_t;
+            match array_cell_typ(&(t1.1).0, (t1.1).1) {
+                Some((cell_t, cell_t_span)) => {
                     if let Borrowing::Borrowed = (t2.0).0 {
                         sess.span_err(e2.1, "cannot index array with a borrowed type");
                         return Err(());
@@ -303,6 +520,7 @@ fn typecheck_expression(
                         | BaseTyp::Isize => Ok((
                             ((Borrowing::Consumed, (t1.0).1), (cell_t, cell_t_span)),
                             var_context,
+                            moved_context,
                         )),
                         _ => {
                             sess.span_err(
@@ -318,8 +536,7 @@ fn typecheck_expression(
                         }
                     }
                 }
-                //TODO: add named arrays
-                _ => {
+                None => {
                     sess.span_err(
                         e1.1,
                         format!(
@@ -355,39 +572,27 @@ fn typecheck_expression(
                     )
                 }
                 let mut var_context = var_context.clone();
+                let mut moved_context = moved_context.clone();
                 for ((_, (sig_t, _)), (arg, arg_span)) in f_sig.args.iter().zip(args) {
-                    let (arg_t, new_var_context) = typecheck_expression(
+                    let (arg_t, new_var_context, new_moved_context) = typecheck_expression(
                         sess,
                         &(arg.clone(), arg_span.clone()),
                         fn_context,
                         &var_context,
+                        &moved_context,
+                        Some(&(sig_t.1).0),
                     )?;
                     var_context = new_var_context;
-                    match ((arg_t.0).0, &sig_t.0) {
-                        (Borrowing::Consumed, &(Borrowing::Borrowed, _)) => {
-                            sess.span_err(*arg_span, "expected a borrow here but didn't find one");
-                            return Err(());
-                        }
-                        (Borrowing::Borrowed, &(Borrowing::Consumed, _)) => {
-                            sess.span_err(
-                                *arg_span,
-                                "superflous borrow here, argument is consumed",
-                            );
-                            return Err(());
-                        }
-                        _ => (),
-                    }
-                    if (arg_t.1).0 != (sig_t.1).0 {
-                        sess.span_err(
-                            *arg_span,
-                            format!("expected type {}, got {}", (sig_t.1).0, (arg_t.1).0).as_str(),
-                        );
+                    moved_context = new_moved_context;
+                    if coerce(&arg_t, sig_t).is_err() {
+                        report_coerce_failure(sess, *arg_span, &arg_t, sig_t);
                         return Err(());
                     }
                 }
                 Ok((
                     ((Borrowing::Consumed, *f_span), f_sig.ret.clone()),
                     var_context,
+                    moved_context,
                 ))
             }
             _ => {
@@ -400,9 +605,11 @@ fn typecheck_expression(
         },
         Expression::MethodCall(sel, _, (f, f_span), args) => {
             let mut var_context = var_context.clone();
-            let (sel_typ, new_var_context) =
-                typecheck_expression(sess, &sel, fn_context, &var_context)?;
+            let mut moved_context = moved_context.clone();
+            let (sel_typ, new_var_context, new_moved_context) =
+                typecheck_expression(sess, &sel, fn_context, &var_context, &moved_context, None)?;
             var_context = new_var_context;
+            moved_context = new_moved_context;
             let f_sig = match fn_context.get(&FnKey::Method((sel_typ.1).0.clone(), f.clone())) {
                 None => {
                     sess.span_err(
@@ -429,35 +636,25 @@ fn typecheck_expression(
                 )
             }
             for ((_, (sig_t, _)), (ref arg, arg_span)) in f_sig.args.iter().zip(args) {
-                let (arg_t, new_var_context) = typecheck_expression(
+                let (arg_t, new_var_context, new_moved_context) = typecheck_expression(
                     sess,
                     &(arg.clone(), arg_span.clone()),
                     fn_context,
                     &var_context,
+                    &moved_context,
+                    Some(&(sig_t.1).0),
                 )?;
                 var_context = new_var_context;
-                match (arg_t.0, &sig_t.0) {
-                    ((Borrowing::Consumed, _), &(Borrowing::Borrowed, _)) => {
-                        sess.span_err(arg_span, "expected a borrow here but didn't find one");
-                        return Err(());
-                    }
-                    ((Borrowing::Borrowed, _), &(Borrowing::Consumed, _)) => {
-                        sess.span_err(arg_span, "superflous borrow here, argument is consumed");
-                        return Err(());
-                    }
-                    _ => (),
-                }
-                if (arg_t.1).0 != (sig_t.1).0 {
-                    sess.span_err(
-                        arg_span,
-                        format!("expected type {}, got {}", (sig_t.1).0, (arg_t.1).0).as_str(),
-                    );
+                moved_context = new_moved_context;
+                if coerce(&arg_t, sig_t).is_err() {
+                    report_coerce_failure(sess, arg_span, &arg_t, sig_t);
                     return Err(());
                 }
             }
             Ok((
                 ((Borrowing::Consumed, *f_span), f_sig.ret.clone()),
                 var_context,
+                moved_context,
             ))
         }
     }
@@ -513,15 +710,23 @@ fn typecheck_statement(
     (s, s_span): &Spanned<Statement>,
     fn_context: &FnContext,
     var_context: &VarContext,
-) -> TypecheckingResult<(Typ, VarContext, VarSet)> {
+    moved_context: &MovedContext,
+) -> TypecheckingResult<(Statement, Typ, VarContext, VarSet, VarSet, MovedContext)> {
     match s {
         Statement::LetBinding((pat, pat_span), typ, expr) => {
-            let (expr_typ, new_var_context) =
-                typecheck_expression(sess, expr, fn_context, var_context)?;
-            match typ {
-                None => (),
+            let expected_typ = typ.as_ref().map(|(t, _)| (t.1).0.clone());
+            let (expr_typ, new_var_context, new_moved_context) = typecheck_expression(
+                sess,
+                expr,
+                fn_context,
+                var_context,
+                moved_context,
+                expected_typ.as_ref(),
+            )?;
+            let binding_typ = match typ {
+                None => expr_typ.clone(),
                 Some((typ, _)) => {
-                    if !equal_types(typ, &expr_typ) {
+                    if coerce(&expr_typ, typ).is_err() {
                         sess.span_err(
                             *pat_span,
                             format!(
@@ -535,17 +740,334 @@ fn typecheck_statement(
                         );
                         return Err(());
                     }
+                    typ.clone()
                 }
             };
             let pat_var_context =
-                typecheck_pattern(sess, &(pat.clone(), pat_span.clone()), &expr_typ)?;
+                typecheck_pattern(sess, &(pat.clone(), pat_span.clone()), &binding_typ)?;
             Ok((
+                s.clone(),
                 ((Borrowing::Consumed, *s_span), (BaseTyp::Unit, *s_span)),
                 new_var_context.clone().union(pat_var_context),
                 HashSet::new(),
+                HashSet::new(),
+                new_moved_context,
+            ))
+        }
+        Statement::Reassignment((x, x_span), expr) => {
+            let existing_typ = match var_context.get(x) {
+                None => {
+                    sess.span_err(
+                        *x_span,
+                        format!("cannot assign to unknown variable {}", x).as_str(),
+                    );
+                    return Err(());
+                }
+                Some(t) => t.clone(),
+            };
+            let (expr_typ, new_var_context, new_moved_context) = typecheck_expression(
+                sess,
+                expr,
+                fn_context,
+                var_context,
+                moved_context,
+                Some(&(existing_typ.1).0),
+            )?;
+            if !equal_types(&existing_typ, &expr_typ) {
+                sess.span_err(
+                    *x_span,
+                    format!(
+                        "wrong type in reassignment of variable {}: expected {}{}, found {}{}",
+                        x,
+                        (existing_typ.0).0,
+                        (existing_typ.1).0,
+                        (expr_typ.0).0,
+                        (expr_typ.1).0
+                    )
+                    .as_str(),
+                );
+                return Err(());
+            }
+            // Reassigning restores the binding even if the right-hand side
+            // moved it away, so uses later in the block see it as live
+            // again, and the gen/kill set grows to include `x` so the
+            // backend knows it must emit a `mut` binding for it. Because
+            // this statement, once reached, unconditionally restores `x`,
+            // it also belongs in the unconditionally-reassigned set used to
+            // check loop bodies for moves that can't be undone.
+            Ok((
+                s.clone(),
+                ((Borrowing::Consumed, *s_span), (BaseTyp::Unit, *s_span)),
+                new_var_context.update(x.clone(), existing_typ),
+                HashSet::unit(x.clone()),
+                HashSet::unit(x.clone()),
+                new_moved_context.without(x),
+            ))
+        }
+        Statement::ArrayUpdate((x, x_span), index, expr) => {
+            let arr_typ = match var_context.get(x) {
+                None => {
+                    match moved_context.get(x) {
+                        Some(moved_span) => {
+                            sess.struct_span_err(
+                                *x_span,
+                                format!("use of moved value `{}`", x).as_str(),
+                            )
+                            .span_note(*moved_span, format!("value `{}` was moved here", x).as_str())
+                            .emit();
+                        }
+                        None => {
+                            sess.span_err(
+                                *x_span,
+                                format!("cannot assign to unknown variable {}", x).as_str(),
+                            );
+                        }
+                    }
+                    return Err(());
+                }
+                Some(t) => t.clone(),
+            };
+            let (cell_typ, _cell_span) = match array_cell_typ(&(arr_typ.1).0, *x_span) {
+                Some(ct) => ct,
+                None => {
+                    sess.span_err(
+                        *x_span,
+                        format!(
+                            "{} should be an array or a sequence but instead has type {}",
+                            x,
+                            (arr_typ.1).0
+                        )
+                        .as_str(),
+                    );
+                    return Err(());
+                }
+            };
+            let (index_typ, var_context, moved_context) = typecheck_expression(
+                sess,
+                index,
+                fn_context,
+                var_context,
+                moved_context,
+                Some(&BaseTyp::Usize),
+            )?;
+            if let Borrowing::Borrowed = (index_typ.0).0 {
+                sess.span_err(index.1, "cannot index array with a borrowed type");
+                return Err(());
+            }
+            if !is_integer(&(index_typ.1).0) {
+                sess.span_err(
+                    index.1,
+                    format!(
+                        "expected an integer to index array but got type {}{}",
+                        (index_typ.0).0,
+                        (index_typ.1).0
+                    )
+                    .as_str(),
+                );
+                return Err(());
+            }
+            let (expr_typ, new_var_context, new_moved_context) = typecheck_expression(
+                sess,
+                expr,
+                fn_context,
+                &var_context,
+                &moved_context,
+                Some(&cell_typ),
+            )?;
+            if (expr_typ.1).0 != cell_typ {
+                sess.span_err(
+                    expr.1,
+                    format!(
+                        "expected array cell type {}{}, got {}{}",
+                        Borrowing::Consumed,
+                        cell_typ,
+                        (expr_typ.0).0,
+                        (expr_typ.1).0
+                    )
+                    .as_str(),
+                );
+                return Err(());
+            }
+            Ok((
+                s.clone(),
+                ((Borrowing::Consumed, *s_span), (BaseTyp::Unit, *s_span)),
+                new_var_context,
+                HashSet::unit(x.clone()),
+                HashSet::new(),
+                new_moved_context,
+            ))
+        }
+        Statement::Conditional((cond, cond_span), (b_t, b_t_span), b_f, mutated_vars_hint) => {
+            let (cond_typ, var_context, moved_context) = typecheck_expression(
+                sess,
+                &(cond.clone(), *cond_span),
+                fn_context,
+                var_context,
+                moved_context,
+                Some(&BaseTyp::Bool),
+            )?;
+            match ((cond_typ.0).0, (cond_typ.1).0) {
+                (Borrowing::Consumed, BaseTyp::Bool) => (),
+                _ => {
+                    sess.span_err(*cond_span, "condition of if statement must be of type bool");
+                    return Err(());
+                }
+            };
+            // Each branch is typechecked from the *same* incoming context:
+            // linearity must be checked independently per path, otherwise a
+            // move made only in one branch would incorrectly be seen from
+            // the other.
+            let (new_b_t, var_context_t, always_reassigned_t, moved_context_t) =
+                typecheck_block(sess, b_t.clone(), fn_context, &var_context, &moved_context)?;
+            let (new_b_f, var_context_f, always_reassigned_f, moved_context_f) = match b_f {
+                None => (None, var_context.clone(), HashSet::new(), moved_context.clone()),
+                Some((b_f, b_f_span)) => {
+                    let (new_b_f, var_context_f, always_reassigned_f, moved_context_f) =
+                        typecheck_block(sess, b_f.clone(), fn_context, &var_context, &moved_context)?;
+                    (Some((new_b_f, *b_f_span)), var_context_f, always_reassigned_f, moved_context_f)
+                }
+            };
+            let merged_var_context = merge_var_context(&var_context_t, &var_context_f);
+            // A value moved on either path might really have been moved,
+            // whichever branch ends up executing, so a later use must be
+            // flagged: union rather than intersect the two moved-value maps.
+            let merged_moved_context = moved_context_t.union(moved_context_f);
+            // Only variables that were already bound before the conditional
+            // are meaningful to the enclosing scope: a branch's own `let`s
+            // are local to it even if reassigned inside that same branch.
+            let mutated_vars: VarSet = new_b_t
+                .mutated_vars
+                .clone()
+                .unwrap_or_default()
+                .into_iter()
+                .collect::<VarSet>()
+                .union(
+                    new_b_f
+                        .as_ref()
+                        .map(|(b, _)| b.mutated_vars.clone().unwrap_or_default())
+                        .unwrap_or_default()
+                        .into_iter()
+                        .collect(),
+                )
+                .into_iter()
+                .filter(|id| var_context.contains_key(id))
+                .collect();
+            // A variable is only *unconditionally* reassigned by this `if`
+            // if every branch reassigns it: unlike `mutated_vars` above
+            // (which unions the two branches, since a `mut` binding is
+            // needed as soon as either path can touch it), this has to be
+            // an intersection, or a variable consumed with no way back on
+            // one path would be mistaken for one that's always restored.
+            // A missing `else` reassigns nothing, so it contributes the
+            // empty set here rather than falling out of the union.
+            let always_reassigned: VarSet = always_reassigned_t
+                .into_iter()
+                .filter(|id| always_reassigned_f.contains(id) && var_context.contains_key(id))
+                .collect();
+            let new_s = Statement::Conditional(
+                (cond.clone(), *cond_span),
+                (new_b_t, *b_t_span),
+                new_b_f,
+                mutated_vars_hint.clone(),
+            );
+            Ok((
+                new_s,
+                ((Borrowing::Consumed, *s_span), (BaseTyp::Unit, *s_span)),
+                merged_var_context,
+                mutated_vars,
+                always_reassigned,
+                merged_moved_context,
+            ))
+        }
+        Statement::ForLoop((x, x_span), (e_start, e_start_span), (e_end, e_end_span), (b, b_span)) => {
+            let (start_typ, var_context, moved_context) = typecheck_expression(
+                sess,
+                &(e_start.clone(), *e_start_span),
+                fn_context,
+                var_context,
+                moved_context,
+                Some(&BaseTyp::Usize),
+            )?;
+            let (end_typ, var_context, moved_context) = typecheck_expression(
+                sess,
+                &(e_end.clone(), *e_end_span),
+                fn_context,
+                &var_context,
+                &moved_context,
+                Some(&BaseTyp::Usize),
+            )?;
+            for (t, t_span) in [(&start_typ, e_start_span), (&end_typ, e_end_span)].iter() {
+                match ((t.0).0, &(t.1).0) {
+                    (Borrowing::Consumed, BaseTyp::Usize) => (),
+                    _ => {
+                        sess.span_err(**t_span, "bounds of a for loop must be of type usize");
+                        return Err(());
+                    }
+                }
+            }
+            let loop_var_context = var_context.update(
+                x.clone(),
+                ((Borrowing::Consumed, *x_span), (BaseTyp::Usize, *x_span)),
+            );
+            // The body may run any number of times (including zero), so a
+            // value consumed during one iteration must still be available
+            // at the start of the next. A variable can only be "given
+            // back" by being reassigned on *every* path through the body:
+            // `always_reassigned` is computed by intersecting branches at
+            // each `if`, unlike the body's `mutated_vars`, which unions them
+            // and so only means "reassigned somewhere" — not enough to
+            // prove the loop can run a second time. One pass over the body
+            // already tells us everything: anything still missing from
+            // `var_context_out` afterwards, and not unconditionally
+            // reassigned, was moved somewhere a loop can't allow.
+            let (new_b, var_context_out, always_reassigned, moved_context_out) =
+                typecheck_block(sess, b.clone(), fn_context, &loop_var_context, &moved_context)?;
+            let mutated_vars = new_b.mutated_vars.clone().unwrap_or_default();
+            let mut moved_for_good = Vec::new();
+            for (id, _) in loop_var_context.iter() {
+                if *id == *x {
+                    continue;
+                }
+                if !var_context_out.contains_key(id) && !always_reassigned.contains(id) {
+                    moved_for_good.push(id.clone());
+                }
+            }
+            if !moved_for_good.is_empty() {
+                for id in moved_for_good {
+                    sess.span_err(
+                        *b_span,
+                        format!(
+                            "cannot move `{}` into a loop body that may run more than once; reassign it before the end of the loop body",
+                            id
+                        )
+                        .as_str(),
+                    );
+                }
+                return Err(());
+            }
+            let final_var_context = restrict(&loop_var_context, &var_context);
+            let final_moved_context = moved_context_out.without(x);
+            let mutated_vars: VarSet = mutated_vars
+                .into_iter()
+                .filter(|id| *id != *x && var_context.contains_key(id))
+                .collect();
+            let new_s = Statement::ForLoop(
+                (x.clone(), *x_span),
+                (e_start.clone(), *e_start_span),
+                (e_end.clone(), *e_end_span),
+                (new_b, *b_span),
+            );
+            // The loop may run zero times, so nothing executed inside it is
+            // guaranteed from the point of view of the enclosing scope.
+            Ok((
+                new_s,
+                ((Borrowing::Consumed, *s_span), (BaseTyp::Unit, *s_span)),
+                final_var_context,
+                mutated_vars,
+                HashSet::new(),
+                final_moved_context,
             ))
         }
-        _ => unimplemented!(),
     }
 }
 
@@ -554,35 +1076,60 @@ fn typecheck_block(
     b: Block,
     fn_context: &FnContext,
     var_context: &VarContext,
-) -> TypecheckingResult<Block> {
-    let mut var_context = var_context.clone();
+    moved_context: &MovedContext,
+) -> TypecheckingResult<(Block, VarContext, VarSet, MovedContext)> {
+    let mut inner_var_context = var_context.clone();
+    let mut inner_moved_context = moved_context.clone();
     let mut mutated_vars = HashSet::new();
+    // Every statement in a block is reached whenever the block itself runs,
+    // so (unlike at an `if`'s two branches) the sets contributed by each
+    // statement in sequence are combined with a union, not an intersection.
+    let mut always_reassigned = HashSet::new();
     let mut return_typ = None;
-    for (i, s) in b.stmts.iter().enumerate() {
-        let (stmt_typ, new_var_context, new_mutated_vars) =
-            typecheck_statement(sess, s, fn_context, &var_context)?;
-        var_context = new_var_context;
+    let mut new_stmts = Vec::new();
+    let stmts_len = b.stmts.len();
+    for (i, s) in b.stmts.into_iter().enumerate() {
+        let s_span = s.1;
+        let (new_s, stmt_typ, new_var_context, new_mutated_vars, new_always_reassigned, new_moved_context) =
+            typecheck_statement(sess, &s, fn_context, &inner_var_context, &inner_moved_context)?;
+        inner_var_context = new_var_context;
+        inner_moved_context = new_moved_context;
         mutated_vars = mutated_vars.clone().union(new_mutated_vars);
-        if i + 1 < b.stmts.len() {
+        always_reassigned = always_reassigned.clone().union(new_always_reassigned);
+        if i + 1 < stmts_len {
             // Statement return types should be unit except for the last one
             match stmt_typ {
                 ((Borrowing::Consumed, _), (BaseTyp::Unit, _)) => (),
                 _ => {
-                    sess.span_err(s.1, "statement shoud have an unit type here");
+                    sess.span_err(s_span, "statement shoud have an unit type here");
                     return Err(());
                 }
             }
         } else {
             return_typ = Some(stmt_typ)
         }
+        new_stmts.push((new_s, s_span));
     }
-    // We don't return a new VarContext because the block is the scope of the variables
-    // defined inside it.
-    Ok(Block {
-        stmts: b.stmts,
-        mutated_vars: Some(mutated_vars.into_iter().collect()),
-        return_typ,
-    })
+    // The block is the scope of the variables it defines, so we only hand
+    // back to the caller the effect this block had on variables that were
+    // already visible before it: bindings created inside it go out of
+    // scope, but a move of an outer variable must still be observed.
+    let outer_var_context = restrict(&inner_var_context, var_context);
+    let outer_moved_context = restrict(&inner_moved_context, var_context);
+    let outer_always_reassigned: VarSet = always_reassigned
+        .into_iter()
+        .filter(|id| var_context.contains_key(id))
+        .collect();
+    Ok((
+        Block {
+            stmts: new_stmts,
+            mutated_vars: Some(mutated_vars.into_iter().collect()),
+            return_typ,
+        },
+        outer_var_context,
+        outer_always_reassigned,
+        outer_moved_context,
+    ))
 }
 
 fn typecheck_item(
@@ -599,11 +1146,9 @@ fn typecheck_item(
                 .fold(var_context, |var_context, ((x, _), (t, _))| {
                     var_context.update(x.clone(), t.clone())
                 });
-            let out = Item::FnDecl(
-                (f.clone(), f_span),
-                sig.clone(),
-                (typecheck_block(sess, b, fn_context, &var_context)?, b_span),
-            );
+            let (new_b, _, _, _) =
+                typecheck_block(sess, b, fn_context, &var_context, &HashMap::new())?;
+            let out = Item::FnDecl((f.clone(), f_span), sig.clone(), (new_b, b_span));
             let fn_context = fn_context.update(FnKey::Static(f), sig);
             Ok((out, fn_context))
         }